@@ -1,5 +1,10 @@
 use std::{
-    ffi::CString,
+    collections::BTreeMap,
+    ffi::{c_void, CString, NulError},
+    fmt,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicI32, Ordering},
     time::{Duration, Instant},
 };
 
@@ -8,16 +13,38 @@ use nix::{
     errno::errno,
     fcntl::OFlag,
     mount::MsFlags,
+    poll::{poll, PollFd, PollFlags},
     sched::{clone, CloneFlags},
     sys::{
         resource::{setrlimit, Resource},
-        signal::{kill, Signal},
+        signal::{kill, SigSet, Signal},
+        signalfd::{SfdFlags, SignalFd},
         stat::Mode,
     },
-    unistd::{chdir, chroot, dup2},
+    unistd::{chdir, chroot, dup2, Pid},
 };
+use seccompiler::{BpfProgram, SeccompAction, SeccompFilter, SeccompRule, TargetArch};
 use serde::Deserialize;
 use tempfile::tempdir;
+use uuid::Uuid;
+
+/// Root under which a transient cgroup v2 directory is created per run.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup/evalsa";
+/// Process/thread cap applied to every sandboxed cgroup, independent of `LaunchOption`.
+const CGROUP_PIDS_MAX: u32 = 64;
+/// Offset of `_sifields._sigsys` inside `siginfo_t` on x86-64 glibc: the three leading
+/// `int`s (`si_signo`, `si_errno`, `si_code`) are padded out to the union's 8-byte
+/// alignment before `_call_addr`/`_syscall`/`_arch` begin.
+const SIGINFO_SIGSYS_OFFSET: usize = 16;
+/// How long [`read_seccomp_violation`] waits for a report after the execute child has
+/// already been reaped, to bound against a descendant the submission forked inheriting
+/// the report pipe's write end and holding it open indefinitely.
+const SECCOMP_REPORT_POLL_TIMEOUT_MS: i32 = 50;
+
+/// fd the `SIGSYS` handler installed by [`install_seccomp`] writes the offending syscall
+/// number to. Set right before the filter is installed; only ever touched by the child
+/// that installed it, so a process-global is safe despite the `unsafe` signal handler.
+static SECCOMP_REPORT_FD: AtomicI32 = AtomicI32::new(-1);
 
 #[derive(Deserialize, Debug)]
 pub struct Language {
@@ -26,12 +53,41 @@ pub struct Language {
     pub compile: Option<String>,
     pub execute: String,
     pub args: Vec<String>,
+    /// Overrides `Sandbox::seccomp` for this language, e.g. to block `ptrace`/`socket`
+    /// for an untrusted interpreter without tightening every other language.
+    pub seccomp: Option<SeccompPolicy>,
+    /// Overrides the worker's global default `LaunchOption` for this language, e.g. a
+    /// longer timeout for a language with a slow interpreter start-up. Falls back to the
+    /// global default when absent; a future per-run override would take priority over both.
+    pub default_launch_option: Option<LaunchOption>,
+    /// Name of another entry in `Config::languages` to run as the checker for this
+    /// language's submissions (see [`run_checker`]). `evalsa_worker_proto::Run` doesn't
+    /// carry a per-run checker spec or expected answer yet, so the checker configured here
+    /// is always invoked with an empty expected answer — enough for a checker that only
+    /// validates output format, not for one that compares against a real answer key.
+    pub checker: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Sandbox {
     pub stack_size_bytes: usize,
     pub mounts: Vec<Mount>,
+    pub seccomp: Option<SeccompPolicy>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SeccompMode {
+    /// `syscalls` is the complete set of permitted syscalls; everything else traps.
+    AllowList,
+    /// `syscalls` is the set of forbidden syscalls; everything else is permitted.
+    DenyList,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SeccompPolicy {
+    pub mode: SeccompMode,
+    pub syscalls: Vec<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -40,10 +96,25 @@ pub struct Mount {
     pub destination: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct LaunchOption {
     pub timeout: u64,
     pub max_virtual_memory: u64,
+    /// Stdout past this many bytes trips `LaunchStatus::OutputLimitExceeded`.
+    #[serde(default = "default_max_stdout_bytes")]
+    pub max_stdout_bytes: usize,
+    /// Stderr capture stops at the first read that takes it past this many bytes (so up to
+    /// one 8 KiB read over), rather than failing the run.
+    #[serde(default = "default_max_stderr_bytes")]
+    pub max_stderr_bytes: usize,
+}
+
+fn default_max_stdout_bytes() -> usize {
+    256 << 20
+}
+
+fn default_max_stderr_bytes() -> usize {
+    2 << 10
 }
 
 #[derive(Deserialize, Debug)]
@@ -53,6 +124,9 @@ pub struct LaunchResult {
     pub stderr: Vec<u8>,
     pub memory_kib: i64,
     pub user_time_ms: i64,
+    /// Verdict from the checker passed to `launch`, if any. `None` when no checker was
+    /// supplied, or the submission didn't reach a normal exit for the checker to judge.
+    pub checker: Option<CheckerVerdict>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -62,103 +136,259 @@ pub enum LaunchStatus {
     RuntimeError,
     OutputLimitExceeded,
     TimeLimitExceeded,
+    MemoryLimitExceeded,
+    CpuTimeLimitExceeded,
+    SecurityViolation { syscall: i64 },
+}
+
+/// Verdict produced by running a custom checker against a solution's output, for problems
+/// whose correct answer isn't a unique string (floating-point tolerance, any-valid-match).
+/// See [`run_checker`].
+#[derive(Deserialize, Debug)]
+pub struct CheckerVerdict {
+    pub accepted: bool,
+    pub score: f64,
+    pub message: Vec<u8>,
 }
 
+/// Infrastructure failures that can abort a [`launch`] before it produces a normal
+/// [`LaunchResult`] — a bad mount source, `ENOMEM` on `clone`, or a malformed
+/// `Language`/`Sandbox` config. These are distinct from a submission merely failing
+/// (which is reported as a `LaunchStatus` instead): a `LaunchError` means the sandbox
+/// itself couldn't be set up, not that the code ran badly. A failure inside the cloned
+/// child, such as `execve` of a missing interpreter binary, is not a `LaunchError`: the
+/// child dies and the run is reported through its `LaunchStatus`.
+#[derive(Debug)]
+pub enum LaunchError {
+    /// Creating the per-run working directory failed.
+    TempDir(std::io::Error),
+    /// A filesystem operation on the run directory or a cgroup file failed.
+    Io(std::io::Error),
+    /// Creating a pipe for stdout/stderr/seccomp reporting failed.
+    Pipe(nix::Error),
+    /// `clone`ing the compile or execute child failed.
+    Clone(nix::Error),
+    /// Bind-mounting or unmounting a `Sandbox` mount failed.
+    Mount(nix::Error),
+    /// Waiting for a child via `signalfd`/`timerfd`/`poll` failed.
+    Wait(nix::Error),
+    /// A raw `nix` syscall wrapper (e.g. reading a pipe) failed; distinct from `Io` since
+    /// `nix::unistd::read` reports an `Errno`, not a `std::io::Error`.
+    Nix(nix::Error),
+    /// A `Language`/`Sandbox`/`SeccompPolicy` value could not be turned into a runnable
+    /// sandbox, e.g. an embedded NUL byte in an executable path, or an unknown syscall
+    /// name in a seccomp policy.
+    InvalidConfig(String),
+}
+
+impl fmt::Display for LaunchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LaunchError::TempDir(err) => write!(f, "failed to create run directory: {err}"),
+            LaunchError::Io(err) => write!(f, "filesystem operation failed: {err}"),
+            LaunchError::Pipe(err) => write!(f, "failed to create pipe: {err}"),
+            LaunchError::Clone(err) => write!(f, "failed to clone sandbox child: {err}"),
+            LaunchError::Mount(err) => write!(f, "failed to (un)mount sandbox filesystem: {err}"),
+            LaunchError::Wait(err) => write!(f, "failed to wait for sandbox child: {err}"),
+            LaunchError::Nix(err) => write!(f, "sandbox syscall failed: {err}"),
+            LaunchError::InvalidConfig(message) => write!(f, "invalid sandbox config: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for LaunchError {}
+
+impl From<NulError> for LaunchError {
+    fn from(err: NulError) -> Self {
+        LaunchError::InvalidConfig(format!("embedded NUL byte: {err}"))
+    }
+}
+
+/// `checker`, if given, is `(checker language, expected answer)`: once the submission
+/// exits normally, [`run_checker`] runs against its stdout and the verdict is attached to
+/// `LaunchResult::checker`. Passing `None` skips the checker stage entirely, as for
+/// problems with a single unique correct output.
 pub fn launch(
     code: &[u8],
     stdin: &[u8],
     language: &Language,
     sandbox: &Sandbox,
     option: &LaunchOption,
-) -> LaunchResult {
-    let dir = tempdir().unwrap();
+    checker: Option<(&Language, &[u8])>,
+) -> Result<LaunchResult, LaunchError> {
+    let dir = tempdir().map_err(LaunchError::TempDir)?;
     let path = dir.path();
-    let source_path = path.join(&language.source);
-    std::fs::write(source_path, code).unwrap();
-    if let Some(compile) = &language.compile {
-        let (stderr_rx, stderr_tx) = nix::unistd::pipe().unwrap();
-        let execute = CString::new("/bin/bash").unwrap();
-        let execute_args = vec![
-            execute.clone(),
-            CString::new("-c").unwrap(),
-            CString::new(compile.as_str()).unwrap(),
-        ];
-        let mut stack = vec![0; 1048576];
-        let pid = unsafe {
-            let path = path.as_os_str().to_owned();
-            clone(
-                Box::new(move || {
-                    chdir(path.as_os_str()).unwrap();
-                    nix::unistd::close(2).unwrap();
-                    dup2(stderr_tx, 2).unwrap();
-                    nix::unistd::close(stderr_tx).ok();
-                    nix::unistd::execv(&execute, &execute_args).unwrap();
-                    0
-                }),
-                &mut stack,
-                CloneFlags::CLONE_NEWNET | CloneFlags::CLONE_NEWUSER,
-                Some(nix::libc::SIGCHLD),
-            )
+    std::fs::write(path.join(&language.source), code).map_err(LaunchError::Io)?;
+    if let Some(result) = run_compile(path, language, option)? {
+        return Ok(result);
+    }
+    std::fs::write(path.join("stdin"), stdin).map_err(LaunchError::Io)?;
+    let outcome = run_execute(path, language, sandbox, option)?;
+    let checker_verdict = match checker {
+        Some((checker_language, answer)) if matches!(outcome.status, LaunchStatus::Exit(_)) => {
+            Some(run_checker(
+                stdin,
+                &outcome.stdout,
+                answer,
+                checker_language,
+                sandbox,
+                option,
+            )?)
         }
-        .unwrap();
-        let deadline = Instant::now() + Duration::from_millis(option.timeout);
-        let mut usage = empty_rusage();
-        let mut wait;
-        let mut wait_status = 0;
+        _ => None,
+    };
+    Ok(LaunchResult {
+        status: outcome.status,
+        stdout: outcome.stdout,
+        stderr: outcome.stderr,
+        memory_kib: outcome.memory_kib,
+        user_time_ms: outcome.user_time_ms,
+        checker: checker_verdict,
+    })
+}
+
+/// Runs a custom checker against a solution's output, for problems whose correct answer
+/// isn't a unique string (floating-point tolerance, any-valid-match). The checker runs
+/// after the solution has exited, against its captured stdout, so it does not interact
+/// with the solution while it runs. `checker` is a `Language`-style compile/execute spec, typically a
+/// pre-built operator binary reached through `sandbox.mounts` rather than per-run source,
+/// so (unlike [`launch`]) no submitted code is written or compiled here. `input`, `output`
+/// (the solution's stdout) and `answer` (the expected answer) are written into the
+/// checker's sandboxed root as `stdin`, `output` and `answer` for it to read; its exit
+/// code decides the verdict (0 = accepted) and an optional leading numeric line on its
+/// stdout overrides the score, matching the conventions most testlib-style checkers use.
+pub fn run_checker(
+    input: &[u8],
+    output: &[u8],
+    answer: &[u8],
+    checker: &Language,
+    sandbox: &Sandbox,
+    option: &LaunchOption,
+) -> Result<CheckerVerdict, LaunchError> {
+    let dir = tempdir().map_err(LaunchError::TempDir)?;
+    let path = dir.path();
+    std::fs::write(path.join("stdin"), input).map_err(LaunchError::Io)?;
+    std::fs::write(path.join("output"), output).map_err(LaunchError::Io)?;
+    std::fs::write(path.join("answer"), answer).map_err(LaunchError::Io)?;
+    let outcome = run_execute(path, checker, sandbox, option)?;
+    let accepted = matches!(outcome.status, LaunchStatus::Exit(0));
+    let score = std::str::from_utf8(&outcome.stdout)
+        .ok()
+        .and_then(|text| text.lines().next())
+        .and_then(|line| line.trim().parse::<f64>().ok())
+        .unwrap_or(if accepted { 1.0 } else { 0.0 });
+    Ok(CheckerVerdict {
+        accepted,
+        score,
+        message: outcome.stderr,
+    })
+}
+
+/// Compiles `language.source` (already written into `path`) with `language.compile`, if
+/// any. Returns `Ok(Some(result))` with a populated `CompilationError` if it fails, or
+/// `Ok(None)` if there's nothing to compile or compilation succeeded, in which case the
+/// caller should proceed to write stdin and call [`run_execute`].
+fn run_compile(
+    path: &Path,
+    language: &Language,
+    option: &LaunchOption,
+) -> Result<Option<LaunchResult>, LaunchError> {
+    let Some(compile) = &language.compile else {
+        return Ok(None);
+    };
+    let (stderr_rx, stderr_tx) = nix::unistd::pipe().map_err(LaunchError::Pipe)?;
+    let execute = CString::new("/bin/bash").unwrap();
+    let execute_args = vec![
+        execute.clone(),
+        CString::new("-c").unwrap(),
+        CString::new(compile.as_str())?,
+    ];
+    let mut stack = vec![0; 1048576];
+    let cgroup = create_cgroup(option.max_virtual_memory)?;
+    let pid = unsafe {
+        let path = path.as_os_str().to_owned();
+        clone(
+            Box::new(move || {
+                chdir(path.as_os_str()).unwrap();
+                nix::unistd::close(2).unwrap();
+                dup2(stderr_tx, 2).unwrap();
+                nix::unistd::close(stderr_tx).ok();
+                nix::unistd::execv(&execute, &execute_args).unwrap();
+                0
+            }),
+            &mut stack,
+            CloneFlags::CLONE_NEWNET | CloneFlags::CLONE_NEWUSER,
+            Some(nix::libc::SIGCHLD),
+        )
+    }
+    .map_err(LaunchError::Clone)?;
+    join_cgroup(&cgroup, pid)?;
+    let deadline = Instant::now() + Duration::from_millis(option.timeout);
+    let (_wait, wait_status, _usage) = wait_for_child(pid, deadline)?;
+    nix::unistd::close(stderr_tx).ok();
+    if !libc::WIFEXITED(wait_status) || libc::WEXITSTATUS(wait_status) != 0 {
+        let mut buffer = vec![0; 8192];
+        let mut stderr = vec![];
         loop {
-            wait = unsafe { wait4(pid.as_raw(), &mut wait_status, libc::WNOHANG, &mut usage) };
-            if wait != 0 {
+            let len = nix::unistd::read(stderr_rx, &mut buffer).map_err(LaunchError::Nix)?;
+            if len == 0 {
                 break;
             }
-            if Instant::now() > deadline {
-                kill(pid, Signal::SIGKILL).unwrap();
-            }
-        }
-        if wait < 0 {
-            panic!("{:?}", std::io::Error::from_raw_os_error(errno()));
-        }
-        nix::unistd::close(stderr_tx).unwrap();
-        if !libc::WIFEXITED(wait_status) || libc::WEXITSTATUS(wait_status) != 0 {
-            let mut buffer = vec![0; 8192];
-            let mut stderr = vec![];
-            loop {
-                let len = nix::unistd::read(stderr_rx, &mut buffer).unwrap();
-                if len == 0 {
-                    break;
-                }
-                stderr.extend_from_slice(&buffer[..len]);
-                if stderr.len() > 2 << 10 {
-                    break;
-                }
+            stderr.extend_from_slice(&buffer[..len]);
+            if stderr.len() > option.max_stderr_bytes {
+                break;
             }
-            return LaunchResult {
-                status: LaunchStatus::CompilationError,
-                stdout: vec![],
-                stderr,
-                memory_kib: 0,
-                user_time_ms: 0,
-            };
         }
+        remove_cgroup(&cgroup)?;
+        return Ok(Some(LaunchResult {
+            status: LaunchStatus::CompilationError,
+            stdout: vec![],
+            stderr,
+            memory_kib: 0,
+            user_time_ms: 0,
+            checker: None,
+        }));
     }
-    let stdin_path = path.join("stdin");
-    std::fs::write(stdin_path, stdin).unwrap();
-    let (stdout_rx, stdout_tx) = nix::unistd::pipe().unwrap();
-    let (stderr_rx, stderr_tx) = nix::unistd::pipe().unwrap();
+    remove_cgroup(&cgroup)?;
+    Ok(None)
+}
+
+/// Result of a single sandboxed execute stage, returned by [`run_execute`].
+struct ExecuteOutcome {
+    status: LaunchStatus,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    memory_kib: i64,
+    user_time_ms: i64,
+}
+
+/// Runs `language.execute` sandboxed inside `path`, which must already contain a `stdin`
+/// file to be opened as fd 0. Shared by [`launch`] (the submission's own execute stage)
+/// and [`run_checker`] (the checker's single stage), so checkers get the same
+/// chroot/namespace/cgroup/rlimit/seccomp machinery as untrusted submissions.
+fn run_execute(
+    path: &Path,
+    language: &Language,
+    sandbox: &Sandbox,
+    option: &LaunchOption,
+) -> Result<ExecuteOutcome, LaunchError> {
+    let (stdout_rx, stdout_tx) = nix::unistd::pipe().map_err(LaunchError::Pipe)?;
+    let (stderr_rx, stderr_tx) = nix::unistd::pipe().map_err(LaunchError::Pipe)?;
+    let (seccomp_rx, seccomp_tx) = nix::unistd::pipe().map_err(LaunchError::Pipe)?;
+    let seccomp_policy = language.seccomp.as_ref().or(sandbox.seccomp.as_ref());
+    let seccomp_program = seccomp_policy.map(build_seccomp_filter).transpose()?;
     let mut stack = vec![0; sandbox.stack_size_bytes];
-    let execute = CString::new(language.execute.clone()).unwrap();
+    let execute = CString::new(language.execute.clone())?;
     let mut execute_args = vec![execute.clone()];
-    execute_args.extend(
-        language
-            .args
-            .iter()
-            .map(|arg| CString::new(arg.clone()).unwrap()),
-    );
+    for arg in &language.args {
+        execute_args.push(CString::new(arg.clone())?);
+    }
     for mount in &sandbox.mounts {
         let destination = path.join(mount.destination.trim_start_matches('/'));
         std::fs::DirBuilder::new()
             .recursive(true)
             .create(destination.as_path())
-            .unwrap();
+            .map_err(LaunchError::Io)?;
         nix::mount::mount(
             Some(mount.source.as_str()),
             destination.as_path(),
@@ -166,8 +396,9 @@ pub fn launch(
             MsFlags::MS_BIND | MsFlags::MS_RDONLY | MsFlags::MS_NOATIME | MsFlags::MS_NODIRATIME,
             Option::<&str>::None,
         )
-        .unwrap();
+        .map_err(LaunchError::Mount)?;
     }
+    let cgroup = create_cgroup(option.max_virtual_memory)?;
     let pid = unsafe {
         let path = path.as_os_str().to_owned();
         clone(
@@ -190,6 +421,26 @@ pub fn launch(
                     option.max_virtual_memory,
                 )
                 .unwrap();
+                // RLIMIT_CPU only has whole-second granularity. Rounding the wall-clock
+                // timeout *up* to the next second would put the CPU soft limit at or
+                // beyond the wall-clock deadline for almost every non-exact-second
+                // timeout, so wait_for_child's SIGKILL would always win and
+                // CpuTimeLimitExceeded could never fire. Floor instead, so a
+                // single-threaded submission that's using the CPU the whole time it runs
+                // still hits the CPU limit first. Sub-second timeouts floor to 0, which
+                // setrlimit would treat as "trap immediately" rather than "no limit", so
+                // clamp to a 1s minimum; those timeouts just can't get a meaningfully
+                // distinct CPU limit given RLIMIT_CPU's granularity.
+                let cpu_time_secs = (option.timeout / 1000).max(1);
+                setrlimit(Resource::RLIMIT_CPU, cpu_time_secs, cpu_time_secs + 1).unwrap();
+                if let Some(program) = &seccomp_program {
+                    install_seccomp(program, seccomp_tx);
+                } else {
+                    // No handler will ever use it; close it now rather than leaking it
+                    // into the submission (and anything it forks) for no reason.
+                    nix::unistd::close(seccomp_tx).ok();
+                }
+                nix::unistd::close(seccomp_rx).ok();
                 nix::unistd::execve::<_, CString>(&execute, &execute_args, &[]).unwrap();
                 0
             }),
@@ -198,68 +449,338 @@ pub fn launch(
             Some(nix::libc::SIGCHLD),
         )
     }
-    .unwrap();
+    .map_err(LaunchError::Clone)?;
+    join_cgroup(&cgroup, pid)?;
+    nix::unistd::close(seccomp_tx).ok();
     let deadline = Instant::now() + Duration::from_millis(option.timeout);
-    let mut usage = empty_rusage();
-    let mut wait;
-    let mut wait_status = 0;
-    loop {
-        wait = unsafe { wait4(pid.as_raw(), &mut wait_status, libc::WNOHANG, &mut usage) };
-        if wait != 0 {
-            break;
-        }
-        if Instant::now() > deadline {
-            kill(pid, Signal::SIGKILL).unwrap();
-        }
-    }
+    let (_wait, wait_status, usage) = wait_for_child(pid, deadline)?;
     for mount in &sandbox.mounts {
         let destination = path.join(mount.destination.trim_start_matches('/'));
-        nix::mount::umount(destination.as_path()).unwrap();
-    }
-    if wait < 0 {
-        panic!("{:?}", std::io::Error::from_raw_os_error(errno()));
+        nix::mount::umount(destination.as_path()).map_err(LaunchError::Mount)?;
     }
-    let mut status = if libc::WIFEXITED(wait_status) {
+    let violated_syscall = read_seccomp_violation(seccomp_rx);
+    let oom_killed = cgroup_oom_killed(&cgroup)?;
+    let memory_kib = cgroup_memory_kib(&cgroup)?;
+    remove_cgroup(&cgroup)?;
+    let mut status = if let Some(syscall) = violated_syscall {
+        LaunchStatus::SecurityViolation { syscall }
+    } else if oom_killed {
+        LaunchStatus::MemoryLimitExceeded
+    } else if libc::WIFEXITED(wait_status) {
         LaunchStatus::Exit(libc::WEXITSTATUS(wait_status))
+    } else if libc::WTERMSIG(wait_status) == libc::SIGXCPU {
+        LaunchStatus::CpuTimeLimitExceeded
     } else if libc::WTERMSIG(wait_status) == libc::SIGKILL {
         LaunchStatus::TimeLimitExceeded
     } else {
         LaunchStatus::RuntimeError
     };
-    nix::unistd::close(stdout_tx).unwrap();
-    nix::unistd::close(stderr_tx).unwrap();
+    nix::unistd::close(stdout_tx).ok();
+    nix::unistd::close(stderr_tx).ok();
     let mut buffer = vec![0; 8192];
     let mut stdout = vec![];
     loop {
-        let len = nix::unistd::read(stdout_rx, &mut buffer).unwrap();
+        let len = nix::unistd::read(stdout_rx, &mut buffer).map_err(LaunchError::Nix)?;
         if len == 0 {
             break;
         }
         stdout.extend_from_slice(&buffer[..len]);
-        if stdout.len() > 256 << 20 {
+        if stdout.len() > option.max_stdout_bytes {
             status = LaunchStatus::OutputLimitExceeded;
         }
     }
     let mut stderr = vec![];
     loop {
-        let len = nix::unistd::read(stderr_rx, &mut buffer).unwrap();
+        let len = nix::unistd::read(stderr_rx, &mut buffer).map_err(LaunchError::Nix)?;
         if len == 0 {
             break;
         }
         stderr.extend_from_slice(&buffer[..len]);
-        if stderr.len() > 2 << 10 {
+        if stderr.len() > option.max_stderr_bytes {
             break;
         }
     }
-    LaunchResult {
+    Ok(ExecuteOutcome {
         status,
         stdout,
         stderr,
-        memory_kib: usage.ru_majflt * 4,
+        memory_kib,
         user_time_ms: usage.ru_utime.tv_sec * 1000 + usage.ru_utime.tv_usec / 1000,
+    })
+}
+
+/// Creates a transient cgroup v2 directory for a single run, capping its memory and
+/// process count. The caller is responsible for placing the child into it with
+/// [`join_cgroup`] and removing it with [`remove_cgroup`] once the run is reaped.
+fn create_cgroup(max_virtual_memory: u64) -> Result<PathBuf, LaunchError> {
+    let path = Path::new(CGROUP_ROOT).join(Uuid::new_v4().to_string());
+    std::fs::create_dir_all(&path).map_err(LaunchError::Io)?;
+    std::fs::write(path.join("memory.max"), max_virtual_memory.to_string())
+        .map_err(LaunchError::Io)?;
+    std::fs::write(path.join("pids.max"), CGROUP_PIDS_MAX.to_string()).map_err(LaunchError::Io)?;
+    Ok(path)
+}
+
+/// Moves a freshly cloned child into `cgroup` by writing its pid to `cgroup.procs`.
+fn join_cgroup(cgroup: &Path, pid: Pid) -> Result<(), LaunchError> {
+    std::fs::write(cgroup.join("cgroup.procs"), pid.as_raw().to_string())
+        .map_err(LaunchError::Io)
+}
+
+/// Reads `memory.events` and reports whether the kernel OOM-killed a process in `cgroup`,
+/// which is the reliable signal that a SIGKILL was due to the memory cap rather than a
+/// wall-clock timeout.
+fn cgroup_oom_killed(cgroup: &Path) -> Result<bool, LaunchError> {
+    let events = std::fs::read_to_string(cgroup.join("memory.events")).map_err(LaunchError::Io)?;
+    Ok(events
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))
+        .and_then(|count| count.trim().parse::<u64>().ok())
+        .unwrap_or(0)
+        > 0)
+}
+
+/// Reads peak memory usage for `cgroup` in KiB, preferring `memory.peak` and falling back
+/// to the instantaneous `memory.current` on kernels that don't expose it yet.
+fn cgroup_memory_kib(cgroup: &Path) -> Result<i64, LaunchError> {
+    let peak = std::fs::read_to_string(cgroup.join("memory.peak"))
+        .or_else(|_| std::fs::read_to_string(cgroup.join("memory.current")))
+        .map_err(LaunchError::Io)?;
+    Ok(peak.trim().parse::<i64>().unwrap_or(0) / 1024)
+}
+
+/// Removes a transient run cgroup once its process has been reaped. The reaped process
+/// can still have live descendants cgroup v2 accounts for (e.g. an orphaned grandchild the
+/// submission forked and didn't wait on), which would otherwise make `rmdir` fail with
+/// `EBUSY` and leak the cgroup directory forever with those processes still inside it
+/// — write `cgroup.kill` first to terminate anything left, then propagate a removal
+/// failure instead of swallowing it.
+fn remove_cgroup(cgroup: &Path) -> Result<(), LaunchError> {
+    std::fs::write(cgroup.join("cgroup.kill"), "1").map_err(LaunchError::Io)?;
+    std::fs::remove_dir(cgroup).map_err(LaunchError::Io)
+}
+
+/// Compiles a [`SeccompPolicy`] into a BPF program, to be installed in the execute child
+/// with [`install_seccomp`] just before `execve`.
+fn build_seccomp_filter(policy: &SeccompPolicy) -> Result<BpfProgram, LaunchError> {
+    // `mismatch_action` applies to every syscall with no entry in `rules`; `match_action`
+    // applies uniformly to every syscall that does have one — seccompiler has no notion of
+    // a per-syscall action, so an allow-list and a deny-list just swap which of the two
+    // roles `Trap` plays. An empty condition vector means "match this syscall
+    // unconditionally", i.e. apply `match_action` to every listed syscall regardless of args.
+    let (mismatch_action, match_action) = match policy.mode {
+        SeccompMode::AllowList => (SeccompAction::Trap, SeccompAction::Allow),
+        SeccompMode::DenyList => (SeccompAction::Allow, SeccompAction::Trap),
+    };
+    let mut rules: BTreeMap<i64, Vec<SeccompRule>> = BTreeMap::new();
+    for name in &policy.syscalls {
+        rules.insert(syscall_number(name)?, vec![]);
+    }
+    let filter = SeccompFilter::new(rules, mismatch_action, match_action, TargetArch::x86_64)
+        .map_err(|err| LaunchError::InvalidConfig(err.to_string()))?;
+    filter
+        .try_into()
+        .map_err(|err: seccompiler::BackendError| LaunchError::InvalidConfig(err.to_string()))
+}
+
+/// Resolves a syscall name to its number on this architecture.
+fn syscall_number(name: &str) -> Result<i64, LaunchError> {
+    Ok((match name {
+        "read" => libc::SYS_read,
+        "write" => libc::SYS_write,
+        "open" => libc::SYS_open,
+        "openat" => libc::SYS_openat,
+        "close" => libc::SYS_close,
+        "getpid" => libc::SYS_getpid,
+        "stat" => libc::SYS_stat,
+        "fstat" => libc::SYS_fstat,
+        "lstat" => libc::SYS_lstat,
+        "access" => libc::SYS_access,
+        "mmap" => libc::SYS_mmap,
+        "munmap" => libc::SYS_munmap,
+        "mprotect" => libc::SYS_mprotect,
+        "brk" => libc::SYS_brk,
+        "ioctl" => libc::SYS_ioctl,
+        "fcntl" => libc::SYS_fcntl,
+        "rt_sigaction" => libc::SYS_rt_sigaction,
+        "rt_sigprocmask" => libc::SYS_rt_sigprocmask,
+        "rt_sigreturn" => libc::SYS_rt_sigreturn,
+        "clone" => libc::SYS_clone,
+        "clone3" => libc::SYS_clone3,
+        "fork" => libc::SYS_fork,
+        "vfork" => libc::SYS_vfork,
+        "execve" => libc::SYS_execve,
+        "execveat" => libc::SYS_execveat,
+        "exit" => libc::SYS_exit,
+        "exit_group" => libc::SYS_exit_group,
+        "wait4" => libc::SYS_wait4,
+        "kill" => libc::SYS_kill,
+        "tgkill" => libc::SYS_tgkill,
+        "socket" => libc::SYS_socket,
+        "connect" => libc::SYS_connect,
+        "bind" => libc::SYS_bind,
+        "listen" => libc::SYS_listen,
+        "accept" => libc::SYS_accept,
+        "sendto" => libc::SYS_sendto,
+        "recvfrom" => libc::SYS_recvfrom,
+        "ptrace" => libc::SYS_ptrace,
+        "prctl" => libc::SYS_prctl,
+        "seccomp" => libc::SYS_seccomp,
+        "unshare" => libc::SYS_unshare,
+        "setns" => libc::SYS_setns,
+        "mount" => libc::SYS_mount,
+        "umount2" => libc::SYS_umount2,
+        "pivot_root" => libc::SYS_pivot_root,
+        "chroot" => libc::SYS_chroot,
+        "reboot" => libc::SYS_reboot,
+        "init_module" => libc::SYS_init_module,
+        "delete_module" => libc::SYS_delete_module,
+        "bpf" => libc::SYS_bpf,
+        "personality" => libc::SYS_personality,
+        "mkdir" => libc::SYS_mkdir,
+        "rmdir" => libc::SYS_rmdir,
+        "unlink" => libc::SYS_unlink,
+        "unlinkat" => libc::SYS_unlinkat,
+        "chmod" => libc::SYS_chmod,
+        "chown" => libc::SYS_chown,
+        other => {
+            return Err(LaunchError::InvalidConfig(format!(
+                "unknown syscall name in seccomp policy: {other}"
+            )))
+        }
+    }) as i64)
+}
+
+/// Installs `program` as the calling process's seccomp filter and registers a `SIGSYS`
+/// handler that reports the offending syscall number to `report_fd` before the process
+/// terminates. Must run in the cloned child, after rlimits are raised and right before
+/// `execve`, since once active it restricts what the child itself is allowed to call.
+fn install_seccomp(program: &BpfProgram, report_fd: i32) {
+    SECCOMP_REPORT_FD.store(report_fd, Ordering::SeqCst);
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle_sigsys as *const () as usize;
+        action.sa_flags = libc::SA_SIGINFO;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGSYS, &action, std::ptr::null_mut());
+    }
+    seccompiler::apply_filter(program).unwrap();
+}
+
+/// `SIGSYS` handler installed by [`install_seccomp`]. The kernel raises `SIGSYS` on the
+/// thread that made a trapped syscall and populates `_sifields._sigsys` in `siginfo_t`
+/// with the offending syscall number; decode it by hand since `libc::siginfo_t` only
+/// exposes the common header fields, not architecture-specific unions.
+extern "C" fn handle_sigsys(_signal: i32, info: *mut libc::siginfo_t, _context: *mut c_void) {
+    #[repr(C)]
+    struct ArchSigsys {
+        call_addr: *mut c_void,
+        syscall: i32,
+        arch: u32,
+    }
+    let sigsys =
+        unsafe { &*((info as *const u8).add(SIGINFO_SIGSYS_OFFSET) as *const ArchSigsys) };
+    let syscall = sigsys.syscall as i64;
+    let fd = SECCOMP_REPORT_FD.load(Ordering::SeqCst);
+    if fd >= 0 {
+        let bytes = syscall.to_ne_bytes();
+        unsafe { libc::write(fd, bytes.as_ptr() as *const c_void, bytes.len()) };
+    }
+    unsafe { libc::_exit(128 + libc::SIGSYS) };
+}
+
+/// Drains the seccomp report pipe for a reported syscall number. The direct child closes
+/// its copy of the write end immediately only when it installs no filter; with a filter
+/// installed the copy stays open across `execve`, and descendants the submission forks
+/// inherit it and can outlive the direct child — bound the wait with a short `poll` so an orphaned descendant
+/// holding the pipe open can't wedge the single-threaded consumer loop forever.
+fn read_seccomp_violation(report_rx: i32) -> Option<i64> {
+    let mut fds = [PollFd::new(report_rx, PollFlags::POLLIN)];
+    let ready = poll(&mut fds, SECCOMP_REPORT_POLL_TIMEOUT_MS).unwrap_or(0) > 0;
+    let mut buffer = [0u8; 8];
+    let len = if ready {
+        nix::unistd::read(report_rx, &mut buffer).unwrap_or(0)
+    } else {
+        0
+    };
+    nix::unistd::close(report_rx).ok();
+    if len == 8 {
+        Some(i64::from_ne_bytes(buffer))
+    } else {
+        None
     }
 }
 
+/// Blocks until `pid` exits or `deadline` passes, without spinning. `SIGCHLD` is delivered
+/// through a `signalfd` and the deadline through a `timerfd`, both watched with a single
+/// `poll`, so the worker sleeps instead of burning a core per in-flight submission. Returns
+/// the same `(wait4 return value, wait status, rusage)` triple the old spin loop produced.
+fn wait_for_child(pid: Pid, deadline: Instant) -> Result<(i32, i32, libc::rusage), LaunchError> {
+    let mut mask = SigSet::empty();
+    mask.add(Signal::SIGCHLD);
+    mask.thread_block().map_err(LaunchError::Wait)?;
+    let mut sigfd =
+        SignalFd::with_flags(&mask, SfdFlags::SFD_NONBLOCK).map_err(LaunchError::Wait)?;
+
+    let timer = unsafe { libc::timerfd_create(libc::CLOCK_MONOTONIC, libc::TFD_NONBLOCK) };
+    if timer < 0 {
+        return Err(LaunchError::Wait(nix::Error::from_i32(errno())));
+    }
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    let deadline_spec = libc::itimerspec {
+        it_interval: libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        },
+        it_value: libc::timespec {
+            tv_sec: remaining.as_secs() as i64,
+            tv_nsec: remaining.subsec_nanos() as i64,
+        },
+    };
+    if unsafe { libc::timerfd_settime(timer, 0, &deadline_spec, std::ptr::null_mut()) } < 0 {
+        let err = LaunchError::Wait(nix::Error::from_i32(errno()));
+        nix::unistd::close(timer).ok();
+        return Err(err);
+    }
+
+    let mut usage = empty_rusage();
+    let mut wait_status = 0;
+    let mut wait;
+    let mut killed = false;
+    loop {
+        wait = unsafe { wait4(pid.as_raw(), &mut wait_status, libc::WNOHANG, &mut usage) };
+        if wait != 0 {
+            break;
+        }
+        let mut fds = [
+            PollFd::new(sigfd.as_raw_fd(), PollFlags::POLLIN),
+            PollFd::new(timer, PollFlags::POLLIN),
+        ];
+        if let Err(err) = poll(&mut fds, -1) {
+            nix::unistd::close(timer).ok();
+            return Err(LaunchError::Wait(err));
+        }
+        // Consume the pending SIGCHLD (blocked, so it stays pending until read) so the next
+        // poll sleeps again instead of returning at once.
+        sigfd.read_signal().ok();
+        if !killed
+            && fds[1]
+                .revents()
+                .unwrap_or(PollFlags::empty())
+                .contains(PollFlags::POLLIN)
+        {
+            killed = true;
+            nix::unistd::read(timer, &mut [0u8; 8]).ok();
+            kill(pid, Signal::SIGKILL).ok();
+        }
+    }
+    nix::unistd::close(timer).ok();
+    if wait < 0 {
+        return Err(LaunchError::Wait(nix::Error::from_i32(errno())));
+    }
+    Ok((wait, wait_status, usage))
+}
+
 fn empty_rusage() -> libc::rusage {
     libc::rusage {
         ru_utime: libc::timeval {
@@ -286,3 +807,33 @@ fn empty_rusage() -> libc::rusage {
         ru_nivcsw: 0,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::sys::wait::{waitpid, WaitStatus};
+    use nix::unistd::{fork, ForkResult};
+
+    /// A `DenyList` filter naming `getpid` should trap that syscall (killing the caller
+    /// with `SIGSYS`) rather than silently allow it, which is what `build_seccomp_filter`
+    /// produced before `match_action` was wired to the listed action instead of `Allow`.
+    #[test]
+    fn deny_list_traps_denied_syscall() {
+        let policy = SeccompPolicy {
+            mode: SeccompMode::DenyList,
+            syscalls: vec!["getpid".to_string()],
+        };
+        let program = build_seccomp_filter(&policy).unwrap();
+        match unsafe { fork() }.unwrap() {
+            ForkResult::Child => {
+                seccompiler::apply_filter(&program).unwrap();
+                unsafe { libc::getpid() };
+                unsafe { libc::_exit(0) };
+            }
+            ForkResult::Parent { child } => {
+                let status = waitpid(child, None).unwrap();
+                assert!(matches!(status, WaitStatus::Signaled(_, Signal::SIGSYS, _)));
+            }
+        }
+    }
+}
@@ -1,17 +1,43 @@
+use std::time::Instant;
+
 use amiquip::{
     Connection, ConsumerMessage, ConsumerOptions, Exchange, Publish, QueueDeclareOptions,
 };
-use evalsa_worker::{launch, Language, LaunchOption, LaunchStatus, Sandbox};
+use evalsa_worker::{launch, Language, LaunchError, LaunchOption, LaunchStatus, Sandbox};
 use evalsa_worker_proto::{Finished, Run, RunResult, Running, RunningState};
 use serde::Deserialize;
+use tracing::{info, info_span, warn};
+
+mod telemetry;
+
+/// Placeholder expected answer passed to a configured checker: `evalsa_worker_proto::Run`
+/// doesn't carry a per-run expected answer yet (see `Language::checker`'s doc comment).
+const EMPTY_ANSWER: &[u8] = &[];
 
 #[derive(Deserialize, Debug)]
 struct Config {
     languages: Vec<Language>,
     sandbox: Sandbox,
+    /// Used for any language that doesn't set its own `default_launch_option`.
+    default_launch_option: LaunchOption,
+}
+
+/// Picks the `LaunchOption` for a run: the language's own default, falling back to the
+/// worker-wide default.
+///
+/// KNOWN LIMITATION: `evalsa_worker_proto::Run` has no per-run limit overrides, so a run
+/// can never tighten or loosen its limits relative to its language's config -- only the
+/// two tiers above exist. Once `Run` grows that field, it needs to take priority over
+/// both tiers here.
+fn resolve_launch_option(language: &Language, config: &Config) -> LaunchOption {
+    language
+        .default_launch_option
+        .clone()
+        .unwrap_or_else(|| config.default_launch_option.clone())
 }
 
 fn main() {
+    telemetry::init();
     let config_file = std::fs::read_to_string("config.toml").unwrap();
     let config: Config = toml::from_str(&config_file).unwrap();
     let mut connection = Connection::insecure_open("amqp://localhost:5672").unwrap();
@@ -29,8 +55,16 @@ fn main() {
         match message {
             ConsumerMessage::Delivery(delivery) => {
                 let run: Run = ciborium::from_reader(delivery.body.as_slice()).unwrap();
+                let span = info_span!(
+                    "run",
+                    run_id = %run.id,
+                    language = %run.language,
+                    wall_time_ms = tracing::field::Empty,
+                    memory_kib = tracing::field::Empty,
+                    status = tracing::field::Empty,
+                );
+                let _enter = span.enter();
                 if let Some(language) = config.languages.iter().find(|&l| l.name == run.language) {
-                    delivery.ack(&channel).unwrap();
                     let mut body = vec![];
                     ciborium::into_writer(
                         &Running {
@@ -41,16 +75,56 @@ fn main() {
                     )
                     .unwrap();
                     apibound.publish(Publish::new(&body, "apibound")).unwrap();
+                    let option = resolve_launch_option(language, &config);
+                    // `Run` doesn't carry a per-run checker spec or expected answer yet
+                    // (see `Language::checker`'s doc comment), so a configured checker is
+                    // always run against an empty expected answer for now.
+                    let checker = language
+                        .checker
+                        .as_ref()
+                        .and_then(|name| config.languages.iter().find(|l| &l.name == name))
+                        .map(|checker_language| (checker_language, EMPTY_ANSWER));
+                    let started_at = Instant::now();
                     let result = launch(
                         &run.code,
                         &run.stdin,
                         language,
                         &config.sandbox,
-                        &LaunchOption {
-                            timeout: 1000,
-                            max_virtual_memory: 1 << 30,
-                        },
+                        &option,
+                        checker,
                     );
+                    span.record("wall_time_ms", started_at.elapsed().as_millis() as i64);
+                    // A transient `LaunchError` means the sandbox itself is broken (bad
+                    // mount, ENOMEM on clone, ...), not that the submission ran badly —
+                    // requeue it instead of dropping the run on the floor or crashing the
+                    // worker. `InvalidConfig` is deterministic (unresolvable syscall name,
+                    // malformed language/sandbox config, ...): it will fail identically on
+                    // every redelivery, so requeuing it would spin the queue forever —
+                    // nack it without requeueing instead.
+                    let result = match result {
+                        Ok(result) => result,
+                        Err(err @ LaunchError::InvalidConfig(_)) => {
+                            warn!(error = %err, "deterministic sandbox config error, dropping run");
+                            delivery.nack(&channel, false).unwrap();
+                            continue;
+                        }
+                        Err(err) => {
+                            warn!(error = %err, "sandbox infrastructure failure, requeuing run");
+                            delivery.nack(&channel, true).unwrap();
+                            continue;
+                        }
+                    };
+                    span.record("memory_kib", result.memory_kib);
+                    span.record("status", tracing::field::debug(&result.status));
+                    // Recording span fields alone emits nothing without an event inside
+                    // the span — this is the one line every successful run actually logs.
+                    info!(
+                        wall_time_ms = started_at.elapsed().as_millis() as i64,
+                        memory_kib = result.memory_kib,
+                        status = ?result.status,
+                        "run finished",
+                    );
+                    delivery.ack(&channel).unwrap();
                     let mut body = vec![];
                     let run_result = match result.status {
                         LaunchStatus::Exit(code) => RunResult::Exit {
@@ -62,6 +136,11 @@ fn main() {
                         LaunchStatus::RuntimeError => RunResult::RuntimeError,
                         LaunchStatus::OutputLimitExceeded => RunResult::OutputLimitExceeded,
                         LaunchStatus::TimeLimitExceeded => RunResult::TimeLimitExceeded,
+                        // evalsa_worker_proto has no dedicated verdict for these yet; surface
+                        // them as a runtime error rather than block on a proto change.
+                        LaunchStatus::MemoryLimitExceeded => RunResult::RuntimeError,
+                        LaunchStatus::CpuTimeLimitExceeded => RunResult::RuntimeError,
+                        LaunchStatus::SecurityViolation { .. } => RunResult::RuntimeError,
                     };
                     ciborium::into_writer(
                         &Running {
@@ -76,6 +155,8 @@ fn main() {
                     )
                     .unwrap();
                     apibound.publish(Publish::new(&body, "apibound")).unwrap();
+                } else {
+                    warn!("no language configured for run; dropping delivery unacked");
                 }
             }
             _ => break,
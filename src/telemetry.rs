@@ -0,0 +1,86 @@
+//! Structured logging for the worker loop.
+//!
+//! [`init`] installs a `tracing` subscriber that formats lines to stderr as before, and
+//! additionally retains the most recent lines in an in-memory ring buffer so a future
+//! admin/health endpoint can surface them without standing up a separate log pipeline.
+//! Use [`recent_logs`] to peek at the buffer or [`flush_logs`] to drain it.
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::prelude::*;
+
+/// Number of most-recent log lines kept in memory for [`recent_logs`]/[`flush_logs`].
+const BUFFER_CAPACITY: usize = 1024;
+
+#[derive(Clone, Default)]
+struct RingBuffer(Arc<Mutex<VecDeque<String>>>);
+
+impl io::Write for RingBuffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut lines = self.0.lock().unwrap();
+        if lines.len() >= BUFFER_CAPACITY {
+            lines.pop_front();
+        }
+        lines.push_back(String::from_utf8_lossy(data).into_owned());
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for RingBuffer {
+    type Writer = RingBuffer;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+static BUFFER: Mutex<Option<RingBuffer>> = Mutex::new(None);
+
+/// Installs the worker's `tracing` subscriber: human-readable lines on stderr, plus the
+/// in-memory buffer that [`recent_logs`] and [`flush_logs`] read from. Call once at
+/// start-up, before the consume loop begins.
+pub fn init() {
+    let buffer = RingBuffer::default();
+    *BUFFER.lock().unwrap() = Some(buffer.clone());
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_writer(io::stderr))
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(buffer),
+        )
+        .init();
+}
+
+/// Returns a snapshot of the most recently logged lines, oldest first, without clearing
+/// the buffer.
+// Not called yet — there's no admin/health endpoint in this binary to call it from. Kept
+// public and exempted from `dead_code` rather than deleted, since it's the intended read
+// side of the buffer `init` already wires up.
+#[allow(dead_code)]
+pub fn recent_logs() -> Vec<String> {
+    with_buffer(|lines| lines.iter().cloned().collect())
+}
+
+/// Drains and returns the buffered log lines, oldest first.
+#[allow(dead_code)] // see recent_logs
+pub fn flush_logs() -> Vec<String> {
+    with_buffer(|lines| lines.drain(..).collect())
+}
+
+fn with_buffer<T>(f: impl FnOnce(&mut VecDeque<String>) -> T) -> T
+where
+    T: Default,
+{
+    match BUFFER.lock().unwrap().as_ref() {
+        Some(buffer) => f(&mut buffer.0.lock().unwrap()),
+        None => T::default(),
+    }
+}